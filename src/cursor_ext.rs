@@ -1,41 +1,75 @@
 use async_trait::async_trait;
-use std::io::{Cursor, Error, Read};
+use encoding_rs::{UTF_16LE, WINDOWS_1252};
+use std::io::{Cursor, Error, ErrorKind, Read};
 use tokio_byteorder::{AsyncReadBytesExt, LittleEndian};
-use tokio::io::{AsyncRead, SeekFrom, AsyncSeekExt};
+use tokio::io::AsyncRead;
 use compress::*;
 
 #[derive(Debug)]
 pub enum DecompressType {
     Zlib,
-    GZip
+    GZip,
+    Zstd,
+    LZ4,
+    Oodle
+}
+
+impl DecompressType {
+    // Resolve a decoder from the name pulled out of PakInfo.compression_methods,
+    // e.g. "Zlib", "Gzip", "Oodle", "Zstd", "LZ4"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Zlib" => Some(Self::Zlib),
+            "Gzip" | "GZip" => Some(Self::GZip),
+            "Zstd" => Some(Self::Zstd),
+            "LZ4" => Some(Self::LZ4),
+            "Oodle" => Some(Self::Oodle),
+            _ => None
+        }
+    }
 }
 
 #[async_trait]
 pub trait CursorExt: AsyncRead {
-    async fn read_decompress(&mut self, size: usize, decompress_type: DecompressType) -> Result<(usize, Vec<u8>), Error>;
+    async fn read_decompress(&mut self, size: usize, decompressed_size: usize, decompress_type: DecompressType) -> Result<(usize, Vec<u8>), Error>;
     async fn read_fstring(&mut self) -> Result<String, Error>;
     async fn read_buffer(&mut self, size: usize) -> Result<Vec<u8>, Error>;
-    async fn seek_index(&mut self, index: i64);
 }
 
 #[async_trait]
 impl<T: AsRef<[u8]> + Unpin + Send> CursorExt for Cursor<T> {
-    async fn read_decompress(&mut self, size: usize, decompress_type: DecompressType) -> Result<(usize, Vec<u8>), Error> {
+    async fn read_decompress(&mut self, size: usize, decompressed_size: usize, decompress_type: DecompressType) -> Result<(usize, Vec<u8>), Error> {
         let buffer = self.read_buffer(size).await?;
         let mut reader = Cursor::new(buffer);
-        let mut decompressed = Vec::new();
-        let mut bytes_read = 0;
+        let mut decompressed;
+        let bytes_read;
 
         match decompress_type {
             DecompressType::Zlib => {
                 let mut decoder = zlib::Decoder::new(reader);
+                decompressed = Vec::new();
                 bytes_read = decoder.read_to_end(&mut decompressed)?;
             },
             DecompressType::GZip => {
                 let mut decoder = flate::Decoder::new(reader);
+                decompressed = Vec::new();
                 bytes_read = decoder.read_to_end(&mut decompressed)?;
             },
-            _ => panic!("Invalid decompression type: {:?}", decompress_type)
+            DecompressType::Zstd => {
+                decompressed = zstd::decode_all(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                bytes_read = decompressed.len();
+            },
+            DecompressType::LZ4 => {
+                let mut compressed = Vec::new();
+                reader.read_to_end(&mut compressed)?;
+                decompressed = lz4_flex::decompress(&compressed, decompressed_size)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                bytes_read = decompressed.len();
+            },
+            DecompressType::Oodle => {
+                decompressed = crate::pak::oodle::decode(&mut reader, decompressed_size)?;
+                bytes_read = decompressed.len();
+            }
         }
 
         Ok((bytes_read, decompressed))
@@ -43,30 +77,24 @@ impl<T: AsRef<[u8]> + Unpin + Send> CursorExt for Cursor<T> {
 
     async fn read_fstring(&mut self) -> Result<String, Error> {
         let mut len = self.read_i32::<LittleEndian>().await?;
-        let mut data = String::default();
 
         if len > 0 {
-            for _ in 0..len-1 {
-                let char = self.read_u8().await?;
-                data.push(char as char);
-            }
+            // ANSICHAR string: len includes the trailing \0
+            let mut bytes = self.read_buffer(len as usize).await?;
+            bytes.pop();
 
-            // discard the last char (\0)
-            let _ = self.read_u8().await?;
+            Ok(match std::str::from_utf8(&bytes) {
+                Ok(s) => s.to_string(),
+                Err(_) => WINDOWS_1252.decode(&bytes).0.into_owned(),
+            })
         } else {
+            // WIDECHAR string: |len| UTF-16LE code units, including the trailing \0
             len = -len;
-            for _ in 0..len {
-                let mut raw_char = self.read_u16::<LittleEndian>().await?;
-                if raw_char & 0xff00 != 0 {
-                    raw_char = '$' as u16;
-                }
-
-                let char = (raw_char & 255) as u8;
-                data.push(char as char);
-            }
-        }
+            let bytes = self.read_buffer(len as usize * 2).await?;
+            let (decoded, _, _) = UTF_16LE.decode(&bytes);
 
-        Ok(data)
+            Ok(decoded.trim_end_matches('\0').to_string())
+        }
     }
 
     async fn read_buffer(&mut self, size: usize) -> Result<Vec<u8>, Error> {
@@ -74,12 +102,52 @@ impl<T: AsRef<[u8]> + Unpin + Send> CursorExt for Cursor<T> {
         tokio::io::AsyncReadExt::read_exact(self, &mut buffer).await?;
         Ok(buffer)
     }
+}
 
-    async fn seek_index(&mut self, index: i64) {
-        if index < 0 {
-            self.seek(SeekFrom::End(index)).await;
-        } else {
-            self.seek(SeekFrom::Start(index as u64)).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_fstring_decodes_ansi() {
+        let mut bytes = 6i32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(b"hello\0");
+        let mut cursor = Cursor::new(bytes);
+
+        assert_eq!(cursor.read_fstring().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn read_fstring_falls_back_to_windows_1252() {
+        // 0xE9 is not valid UTF-8 on its own, but is 'é' in Windows-1252
+        let mut bytes = 2i32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0xE9, 0x00]);
+        let mut cursor = Cursor::new(bytes);
+
+        assert_eq!(cursor.read_fstring().await.unwrap(), "é");
+    }
+
+    #[tokio::test]
+    async fn read_fstring_decodes_utf16() {
+        let text = "héllo";
+        let mut units: Vec<u16> = text.encode_utf16().collect();
+        units.push(0);
+
+        let mut bytes = (-(units.len() as i32)).to_le_bytes().to_vec();
+        for unit in &units {
+            bytes.extend_from_slice(&unit.to_le_bytes());
         }
+        let mut cursor = Cursor::new(bytes);
+
+        assert_eq!(cursor.read_fstring().await.unwrap(), text);
+    }
+
+    #[tokio::test]
+    async fn read_fstring_empty_string() {
+        // Empty FStrings are serialized as length 0, with no payload at all
+        let bytes = 0i32.to_le_bytes().to_vec();
+        let mut cursor = Cursor::new(bytes);
+
+        assert_eq!(cursor.read_fstring().await.unwrap(), "");
     }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use std::io::{Error, ErrorKind, SeekFrom};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Random-access byte source backing a `PakReader`. Only the footer, index,
+/// and the blocks of a requested entry are ever read through this trait, so
+/// a file-backed implementation never has to hold the whole pak in memory.
+#[async_trait]
+pub trait BlockIO: Send {
+    async fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, Error>;
+    async fn len(&mut self) -> Result<u64, Error>;
+}
+
+pub(crate) struct MemoryBlockIO {
+    buffer: Vec<u8>,
+}
+
+impl MemoryBlockIO {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        Self { buffer }
+    }
+}
+
+#[async_trait]
+impl BlockIO for MemoryBlockIO {
+    async fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let start = offset as usize;
+        let end = start + len;
+
+        if end > self.buffer.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "read past end of buffer"));
+        }
+
+        Ok(self.buffer[start..end].to_vec())
+    }
+
+    async fn len(&mut self) -> Result<u64, Error> {
+        Ok(self.buffer.len() as u64)
+    }
+}
+
+pub(crate) struct FileBlockIO {
+    file: File,
+}
+
+impl FileBlockIO {
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+}
+
+#[async_trait]
+impl BlockIO for FileBlockIO {
+    async fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        self.file.seek(SeekFrom::Start(offset)).await?;
+
+        let mut buffer = vec![0u8; len];
+        self.file.read_exact(&mut buffer).await?;
+        Ok(buffer)
+    }
+
+    async fn len(&mut self) -> Result<u64, Error> {
+        Ok(self.file.metadata().await?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_block_io_reads_in_bounds_slice() {
+        let mut io = MemoryBlockIO::new(b"hello world".to_vec());
+
+        assert_eq!(io.read_at(6, 5).await.unwrap(), b"world");
+        assert_eq!(io.len().await.unwrap(), 11);
+    }
+
+    #[tokio::test]
+    async fn memory_block_io_rejects_read_past_end() {
+        let mut io = MemoryBlockIO::new(b"hello".to_vec());
+
+        let err = io.read_at(3, 10).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
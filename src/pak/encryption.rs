@@ -0,0 +1,57 @@
+use aes::Aes256;
+use aes::cipher::{BlockDecrypt, KeyInit, generic_array::GenericArray};
+
+/// UE pak encryption keys are selected by the 16-byte guid parsed out of the
+/// footer as `PakInfo.encryption_index_guid`.
+pub type Guid = Vec<u8>;
+
+// Shipping paks are encrypted with AES-256 in ECB mode: no IV, every 16-byte
+// block decrypted independently with the same key.
+pub(crate) fn decrypt_ecb_in_place(key: &[u8; 32], data: &mut [u8]) {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    for block in data.chunks_mut(16) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+}
+
+pub(crate) fn round_up_to_block(size: usize) -> usize {
+    (size + 15) & !15
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncrypt;
+
+    fn test_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        key
+    }
+
+    #[test]
+    fn decrypt_ecb_in_place_reverses_encryption() {
+        let key = test_key();
+        let plaintext = b"sixteen byte!!!!pad me to 32byte";
+        let mut data = plaintext.to_vec();
+
+        let cipher = Aes256::new(GenericArray::from_slice(&key));
+        for block in data.chunks_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        }
+        assert_ne!(data, plaintext);
+
+        decrypt_ecb_in_place(&key, &mut data);
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn round_up_to_block_rounds_to_next_multiple_of_16() {
+        assert_eq!(round_up_to_block(0), 0);
+        assert_eq!(round_up_to_block(1), 16);
+        assert_eq!(round_up_to_block(16), 16);
+        assert_eq!(round_up_to_block(17), 32);
+    }
+}
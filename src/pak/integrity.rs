@@ -0,0 +1,8 @@
+use sha1::{Digest, Sha1};
+
+// PakEntry.hash and PakInfo.index_hash are both raw SHA-1 digests
+pub(crate) fn sha1(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
@@ -1,3 +1,7 @@
+pub mod block_io;
+pub mod encryption;
+pub(crate) mod integrity;
+pub(crate) mod oodle;
 pub mod pak_file;
 pub(crate) mod pak_reader;
 
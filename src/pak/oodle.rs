@@ -0,0 +1,47 @@
+use std::io::{Cursor, Error, ErrorKind};
+#[cfg(feature = "oodle")]
+use std::io::Read;
+
+// Oodle has no pure-Rust decoder; this module is a thin wrapper around the
+// proprietary oo2core library, only compiled in when the `oodle` feature is
+// enabled. Without it, Oodle-compressed entries surface as an error rather
+// than failing to build.
+#[cfg(feature = "oodle")]
+mod ffi {
+    #[link(name = "oo2core")]
+    extern "C" {
+        pub fn OodleLZ_Decompress(
+            compressed: *const u8,
+            compressed_size: usize,
+            decompressed: *mut u8,
+            decompressed_size: usize,
+        ) -> i32;
+    }
+}
+
+#[cfg(feature = "oodle")]
+pub(crate) fn decode(reader: &mut Cursor<Vec<u8>>, decompressed_size: usize) -> Result<Vec<u8>, Error> {
+    let mut compressed = Vec::new();
+    reader.read_to_end(&mut compressed)?;
+
+    let mut decompressed = vec![0u8; decompressed_size];
+    let written = unsafe {
+        ffi::OodleLZ_Decompress(
+            compressed.as_ptr(),
+            compressed.len(),
+            decompressed.as_mut_ptr(),
+            decompressed.len(),
+        )
+    };
+
+    if written <= 0 || written as usize != decompressed_size {
+        return Err(Error::new(ErrorKind::InvalidData, "Oodle decompression failed"));
+    }
+
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "oodle"))]
+pub(crate) fn decode(_reader: &mut Cursor<Vec<u8>>, _decompressed_size: usize) -> Result<Vec<u8>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "Oodle support requires the `oodle` feature and the proprietary oo2core library"))
+}
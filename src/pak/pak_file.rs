@@ -1,21 +1,61 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
 use thiserror::Error;
-use tokio::{fs::File, io::AsyncReadExt};
+use tokio::fs::File;
+use tokio::sync::Semaphore;
 
 use super::pak_reader::{PakReader, PakReaderError};
 use std::collections::HashMap;
+use crate::pak::encryption::Guid;
 use crate::pak::pak_reader::PakEntry;
 
+// Caps how many entries extract_to_parallel reads/writes at once, so a pak
+// with tens of thousands of entries doesn't open that many files concurrently
+const MAX_PARALLEL_EXTRACTIONS: usize = 64;
+
 #[derive(Error, Debug)]
 pub enum PakError {
     #[error("Error opening or reading file: {0}")]
     FileError(#[from] std::io::Error),
     #[error("Error parsing the pak file: {0}")]
     ReaderError(#[from] PakReaderError),
+    #[error("Error joining a parallel extraction task: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[error("Parallel extraction requires a PakFile opened with from_path")]
+    ParallelExtractionRequiresPath,
+    #[error("Entry path escapes the extraction directory: {0}")]
+    UnsafeEntryPath(String),
 }
 
-#[derive(Debug)]
+// Rejects entry/mount-point paths that could escape the extraction
+// directory (absolute paths, `..` components, or path prefixes), so a
+// malicious pak can't write outside `dir` via a crafted file_name
+fn sanitize_relative_path(path: &Path) -> Result<PathBuf, PakError> {
+    let mut sanitized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(PakError::UnsafeEntryPath(path.display().to_string()));
+            }
+        }
+    }
+
+    Ok(sanitized)
+}
+
+// Tracks how a PakFile was opened so extract_to_parallel can reopen
+// independent file handles for concurrent entry reads
+#[derive(Debug, Clone)]
+enum PakSource {
+    Path(PathBuf, HashMap<Guid, [u8; 32]>),
+    Other,
+}
+
+#[derive(Debug, Clone)]
 pub struct PakInfo {
     pub encryption_index_guid: Vec<u8>,
     pub is_encrypted: bool,
@@ -29,7 +69,7 @@ pub struct PakInfo {
     pub compression_methods: Vec<String>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PakCompressionBlock {
     pub compression_start: i64,
     pub compression_end: i64
@@ -40,31 +80,44 @@ pub struct PakFile {
     pub info: PakInfo,
     pub mount_point: String,
     pub file_indexes: HashMap<String, PakEntry>,
-    reader: PakReader
+    reader: PakReader,
+    source: PakSource
 }
 
 impl PakFile {
-    // Open the file and pass it to the from_file
+    // Open the file, backed by a lazy file-reading PakReader that never
+    // buffers more than the footer, index, and requested entries
     pub async fn from_path(path: &Path) -> Result<Self, PakError> {
+        Self::from_path_with_keys(path, HashMap::new()).await
+    }
+
+    // Open an encrypted file, supplying the AES-256 keys needed to decrypt it
+    pub async fn from_path_with_keys(path: &Path, keys: HashMap<Guid, [u8; 32]>) -> Result<Self, PakError> {
         match File::open(path).await {
-            Ok(file) => Self::from_file(file).await,
+            Ok(file) => Self::from_reader(PakReader::from_file_with_keys(file, keys.clone()), PakSource::Path(path.to_path_buf(), keys)).await,
             Err(err) => Err(PakError::FileError(err)),
         }
     }
 
-    // Read the buffer from the file, and pass it into from_memory which reads the content
-    pub async fn from_file(mut file: File) -> Result<Self, PakError> {
-        let mut buffer = Vec::new();
-        match file.read_to_end(&mut buffer).await {
-            Ok(_) => Self::from_memory(buffer).await,
-            Err(e) => Err(PakError::FileError(e)),
-        }
+    // Wrap an already-open file without buffering its contents into memory
+    pub async fn from_file(file: File) -> Result<Self, PakError> {
+        Self::from_file_with_keys(file, HashMap::new()).await
+    }
+
+    pub async fn from_file_with_keys(file: File, keys: HashMap<Guid, [u8; 32]>) -> Result<Self, PakError> {
+        Self::from_reader(PakReader::from_file_with_keys(file, keys), PakSource::Other).await
     }
 
     // Parse the PAK from memory, into the pak struct, with respective reader
     pub async fn from_memory(buffer: Vec<u8>) -> Result<Self, PakError> {
-        let mut reader = PakReader::new(buffer);
+        Self::from_memory_with_keys(buffer, HashMap::new()).await
+    }
 
+    pub async fn from_memory_with_keys(buffer: Vec<u8>, keys: HashMap<Guid, [u8; 32]>) -> Result<Self, PakError> {
+        Self::from_reader(PakReader::with_keys(buffer, keys), PakSource::Other).await
+    }
+
+    async fn from_reader(mut reader: PakReader, source: PakSource) -> Result<Self, PakError> {
         let pak_info = reader.get_pak_info().await?;
         let (mount_point, indexes) = reader.get_pak_entries(&pak_info).await?;
 
@@ -72,17 +125,200 @@ impl PakFile {
             info: pak_info,
             mount_point: mount_point,
             file_indexes: indexes,
-            reader: reader
+            reader: reader,
+            source
         })
     }
 
     pub async fn get_entry_data<T: Into<String>>(&mut self, index: T) -> Result<Option<Vec<u8>>, PakError> {
+        self.get_entry_data_internal(index, false).await
+    }
+
+    // Same as get_entry_data, but hashes the decompressed bytes against
+    // PakEntry.hash and returns PakReaderError::HashMismatch on corruption
+    pub async fn get_entry_data_verified<T: Into<String>>(&mut self, index: T) -> Result<Option<Vec<u8>>, PakError> {
+        self.get_entry_data_internal(index, true).await
+    }
+
+    async fn get_entry_data_internal<T: Into<String>>(&mut self, index: T, verify: bool) -> Result<Option<Vec<u8>>, PakError> {
         let entry = self.file_indexes.get(&index.into());
         if let Some(pak_entry) = entry {
-            Ok(Some(self.reader.get_pak_entry_data(pak_entry).await?))
+            Ok(Some(self.reader.get_pak_entry_data(pak_entry, &self.info, verify).await?))
         } else {
             Ok(None)
         }
     }
 
+    // Re-reads the index bytes and checks them against PakInfo.index_hash
+    pub async fn verify_index(&mut self) -> Result<(), PakError> {
+        Ok(self.reader.verify_index(&self.info).await?)
+    }
+
+    fn relative_output_path(&self, file_name: &str) -> Result<PathBuf, PakError> {
+        let mount_point = sanitize_relative_path(Path::new(&self.mount_point))?;
+        let file_name = sanitize_relative_path(Path::new(file_name))?;
+        Ok(mount_point.join(file_name))
+    }
+
+    // Extracts every entry (or those passing `filter`) under `dir`, rebuilding
+    // the mount-point directory tree. `on_progress` is called with the file
+    // name and bytes written after each entry so a caller can drive a
+    // progress bar over large paks.
+    pub async fn extract_to<F: FnMut(&str, u64)>(
+        &mut self,
+        dir: &Path,
+        filter: Option<&dyn Fn(&str) -> bool>,
+        mut on_progress: F,
+    ) -> Result<(), PakError> {
+        let file_names: Vec<String> = self.file_indexes.keys()
+            .filter(|name| filter.map_or(true, |f| f(name)))
+            .cloned()
+            .collect();
+
+        for file_name in file_names {
+            let data = match self.get_entry_data(&file_name).await? {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let out_path = dir.join(self.relative_output_path(&file_name)?);
+            if let Some(parent) = out_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            tokio::fs::write(&out_path, &data).await?;
+            on_progress(&file_name, data.len() as u64);
+        }
+
+        Ok(())
+    }
+
+    // Same as extract_to, but extracts entries concurrently via tokio::spawn.
+    // Each task opens its own file handle, since entries are independent once
+    // the index has been read. Only available for paks opened with from_path,
+    // since a fresh handle has to be reopened per task.
+    pub async fn extract_to_parallel(
+        &self,
+        dir: &Path,
+        filter: Option<&(dyn Fn(&str) -> bool + Sync)>,
+        on_progress: Option<Arc<dyn Fn(&str, u64) + Send + Sync>>,
+    ) -> Result<(), PakError> {
+        let (path, keys) = match &self.source {
+            PakSource::Path(path, keys) => (path.clone(), keys.clone()),
+            PakSource::Other => return Err(PakError::ParallelExtractionRequiresPath),
+        };
+
+        let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_EXTRACTIONS));
+        let mut tasks = Vec::new();
+
+        for (file_name, entry) in &self.file_indexes {
+            if let Some(filter) = filter {
+                if !filter(file_name) {
+                    continue;
+                }
+            }
+
+            let path = path.clone();
+            let keys = keys.clone();
+            let entry = entry.clone();
+            let info = self.info.clone();
+            let file_name = file_name.clone();
+            let out_path = dir.join(self.relative_output_path(&file_name)?);
+            let on_progress = on_progress.clone();
+            let semaphore = semaphore.clone();
+
+            tasks.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("semaphore is never closed");
+
+                let file = File::open(&path).await?;
+                let mut reader = PakReader::from_file_with_keys(file, keys);
+                let data = reader.get_pak_entry_data(&entry, &info, false).await?;
+
+                if let Some(parent) = out_path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+
+                tokio::fs::write(&out_path, &data).await?;
+
+                if let Some(on_progress) = on_progress {
+                    on_progress(&file_name, data.len() as u64);
+                }
+
+                Ok::<(), PakError>(())
+            }));
+        }
+
+        for task in tasks {
+            task.await??;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pak::pak_reader::PakReader;
+
+    fn test_pak_file(mount_point: &str) -> PakFile {
+        PakFile {
+            info: PakInfo {
+                encryption_index_guid: vec![],
+                is_encrypted: false,
+                magic: 0,
+                version: 0,
+                sub_version: 0,
+                index_offset: 0,
+                index_size: 0,
+                index_hash: vec![],
+                index_frozen: 0,
+                compression_methods: vec![],
+            },
+            mount_point: mount_point.to_string(),
+            file_indexes: HashMap::new(),
+            reader: PakReader::new(vec![]),
+            source: PakSource::Other,
+        }
+    }
+
+    #[test]
+    fn sanitize_relative_path_rejects_parent_dir_traversal() {
+        let err = sanitize_relative_path(Path::new("../../etc/passwd")).unwrap_err();
+        assert!(matches!(err, PakError::UnsafeEntryPath(_)));
+    }
+
+    #[test]
+    fn sanitize_relative_path_rejects_absolute_paths() {
+        let err = sanitize_relative_path(Path::new("/etc/passwd")).unwrap_err();
+        assert!(matches!(err, PakError::UnsafeEntryPath(_)));
+    }
+
+    #[test]
+    fn sanitize_relative_path_accepts_normal_relative_paths() {
+        let sanitized = sanitize_relative_path(Path::new("assets/textures/foo.uasset")).unwrap();
+        assert_eq!(sanitized, Path::new("assets/textures/foo.uasset"));
+    }
+
+    #[test]
+    fn relative_output_path_rejects_traversal_in_file_name() {
+        let pak = test_pak_file("Game/Content/");
+        let err = pak.relative_output_path("../../etc/passwd").unwrap_err();
+        assert!(matches!(err, PakError::UnsafeEntryPath(_)));
+    }
+
+    #[test]
+    fn relative_output_path_rejects_traversal_in_mount_point() {
+        let pak = test_pak_file("../../../../");
+        let err = pak.relative_output_path("assets/foo.uasset").unwrap_err();
+        assert!(matches!(err, PakError::UnsafeEntryPath(_)));
+    }
+
+    #[test]
+    fn relative_output_path_joins_normal_entries() {
+        let pak = test_pak_file("Game/Content/");
+        let out_path = pak.relative_output_path("assets/foo.uasset").unwrap();
+        assert_eq!(out_path, Path::new("Game/Content/assets/foo.uasset"));
+    }
 }
@@ -1,11 +1,15 @@
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::Cursor;
 use thiserror::Error;
+use tokio::fs::File;
 use tokio_byteorder::{LittleEndian, BigEndian, AsyncReadBytesExt};
 use std::collections::HashMap;
 
 use super::pak_file::PakInfo;
 use super::{PakVersions, PakVersionSizes};
 use crate::cursor_ext::{CursorExt, DecompressType};
+use crate::pak::block_io::{BlockIO, FileBlockIO, MemoryBlockIO};
+use crate::pak::encryption::{decrypt_ecb_in_place, round_up_to_block, Guid};
+use crate::pak::integrity::sha1;
 use crate::pak::pak_file::PakCompressionBlock;
 
 // static PAK file magic
@@ -24,11 +28,15 @@ pub enum PakReaderError {
     MagicMismatch,
     #[error("Unknown version")]
     UnknownVersion,
-    #[error("Encryption not supported")]
-    EncryptionNotSupported
+    #[error("No decryption key supplied for encryption guid {0:?}")]
+    MissingEncryptionKey(Guid),
+    #[error("Unsupported compression method: {0}")]
+    UnsupportedCompression(String),
+    #[error("Hash mismatch: expected {expected:?}, got {actual:?}")]
+    HashMismatch { expected: Vec<u8>, actual: Vec<u8> }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PakEntry {
     start: u64,
     offset: u64,
@@ -43,16 +51,50 @@ pub struct PakEntry {
     header_size: u64
 }
 
-#[derive(Debug)]
 pub(crate) struct PakReader {
-    reader: Cursor<Vec<u8>>,
+    io: Box<dyn BlockIO>,
+    keys: HashMap<Guid, [u8; 32]>,
+}
+
+impl std::fmt::Debug for PakReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PakReader").finish()
+    }
 }
 
 impl PakReader {
     pub fn new(buffer: Vec<u8>) -> Self {
-        Self {
-            reader: Cursor::new(buffer)
-        }
+        Self::with_keys(buffer, HashMap::new())
+    }
+
+    pub fn with_keys(buffer: Vec<u8>, keys: HashMap<Guid, [u8; 32]>) -> Self {
+        Self::from_io(Box::new(MemoryBlockIO::new(buffer)), keys)
+    }
+
+    pub fn from_file(file: File) -> Self {
+        Self::from_file_with_keys(file, HashMap::new())
+    }
+
+    pub fn from_file_with_keys(file: File, keys: HashMap<Guid, [u8; 32]>) -> Self {
+        Self::from_io(Box::new(FileBlockIO::new(file)), keys)
+    }
+
+    fn from_io(io: Box<dyn BlockIO>, keys: HashMap<Guid, [u8; 32]>) -> Self {
+        Self { io, keys }
+    }
+
+    fn get_encryption_key(&self, info: &PakInfo) -> Result<[u8; 32], PakReaderError> {
+        self.keys.get(&info.encryption_index_guid)
+            .copied()
+            .ok_or_else(|| PakReaderError::MissingEncryptionKey(info.encryption_index_guid.clone()))
+    }
+
+    // compression_index is a 1-based index into PakInfo.compression_methods
+    fn resolve_compression_method(info: &PakInfo, compression_index: u32) -> Result<DecompressType, PakReaderError> {
+        let name = info.compression_methods.get(compression_index as usize - 1)
+            .ok_or_else(|| PakReaderError::UnsupportedCompression(format!("<index {}>", compression_index)))?;
+
+        DecompressType::from_name(name).ok_or_else(|| PakReaderError::UnsupportedCompression(name.clone()))
     }
 
     pub async fn get_pak_info(&mut self) -> Result<PakInfo, PakReaderError> {
@@ -68,7 +110,7 @@ impl PakReader {
     }
 
     pub async fn get_pak_entries(&mut self, info: &PakInfo) -> Result<PakData, PakReaderError> {
-        let index = self.read_pak_index(info).await?;
+        let index = self.read_pak_index(info, false).await?;
         let mut reader = Cursor::new(index);
 
         let mut entries = HashMap::new();
@@ -87,38 +129,81 @@ impl PakReader {
         Ok((mount_point, entries))
     }
 
-    pub async fn get_pak_entry_data(&mut self, entry: &PakEntry) -> Result<Vec<u8>, PakReaderError> {
-        self.reader.set_position(entry.offset + entry.header_size);
+    pub async fn get_pak_entry_data(&mut self, entry: &PakEntry, info: &PakInfo, verify: bool) -> Result<Vec<u8>, PakReaderError> {
+        let payload_start = entry.offset + entry.header_size;
+        let is_entry_encrypted = entry.flags & 0x01 != 0;
+
+        let data = if entry.compression_index == 0 {
+            let read_size = if is_entry_encrypted {
+                round_up_to_block(entry.size as usize)
+            } else {
+                entry.size as usize
+            };
+
+            let mut buffer = self.io.read_at(payload_start, read_size).await?;
+
+            if is_entry_encrypted {
+                let key = self.get_encryption_key(info)?;
+                decrypt_ecb_in_place(&key, &mut buffer);
+                buffer.truncate(entry.size as usize);
+            }
 
-        if entry.compression_index == 0 {
-            Ok(self.reader.read_buffer(entry.size as usize).await?)
+            buffer
         } else {
+            // From PakVersions::RelativeChunkOffsets onward, compression_start/end
+            // are relative to entry.offset (the entry's own header position),
+            // not absolute file offsets
+            let relative_chunk_offsets = info.version >= PakVersions::RelativeChunkOffsets as i32;
+
             let mut index = 0;
             let mut offset = 0;
             let mut decompressed = vec![0u8; entry.uncompressed_size as usize];
 
             for block in &entry.compression_blocks {
                 let uncompressed_block_size = (entry.uncompressed_size - entry.compression_block_size as u64 * index).min(entry.compression_block_size as u64);
-
                 let compressed_size = block.get_size() as usize;
-                let compressed_buffer = self.reader.read_buffer(uncompressed_block_size as usize).await?;
-                let mut compression_reader = Cursor::new(compressed_buffer);
-                let compression_method = match entry.compression_index {
-                    1 => DecompressType::Zlib,
-                    2 => DecompressType::GZip,
-                    _ => panic!("invalid/unsupported compression index for compressed block")
+
+                let block_offset = if relative_chunk_offsets {
+                    entry.offset + block.compression_start as u64
+                } else {
+                    block.compression_start as u64
+                };
+
+                let read_size = if is_entry_encrypted {
+                    round_up_to_block(compressed_size)
+                } else {
+                    compressed_size
                 };
 
-                let (bytes_read, decompressed_bytes) = compression_reader.read_decompress(compressed_size, compression_method).await?;
-                decompressed.splice(offset..bytes_read, decompressed_bytes.iter().cloned());
+                let mut compressed_buffer = self.io.read_at(block_offset, read_size).await?;
+
+                if is_entry_encrypted {
+                    let key = self.get_encryption_key(info)?;
+                    decrypt_ecb_in_place(&key, &mut compressed_buffer);
+                    compressed_buffer.truncate(compressed_size);
+                }
+
+                let mut compression_reader = Cursor::new(compressed_buffer);
+                let compression_method = Self::resolve_compression_method(info, entry.compression_index)?;
+
+                let (bytes_read, decompressed_bytes) = compression_reader.read_decompress(compressed_size, uncompressed_block_size as usize, compression_method).await?;
+                decompressed[offset..offset + bytes_read].copy_from_slice(&decompressed_bytes[..bytes_read]);
 
-                offset += bytes_read;
+                offset += uncompressed_block_size as usize;
                 index += 1;
             }
 
-            Ok(decompressed)
+            decompressed
+        };
+
+        if verify {
+            let actual = sha1(&data);
+            if actual != entry.hash {
+                return Err(PakReaderError::HashMismatch { expected: entry.hash.clone(), actual });
+            }
         }
 
+        Ok(data)
     }
 
     async fn read_pak_entry(&mut self, reader: &mut Cursor<Vec<u8>>, info: &PakInfo) -> Result<PakEntry, PakReaderError> {
@@ -191,27 +276,48 @@ impl PakReader {
         })
     }
 
-    async fn read_pak_index(&mut self, info: &PakInfo) -> Result<Vec<u8>, PakReaderError> {
-        let position = self.reader.position();
-        self.reader.seek_index(info.index_offset).await;
-        let buffer = self.reader.read_buffer(info.index_size as usize).await?;
-        self.reader.set_position(position);
-        // TODO: decrypt memory
+    pub async fn verify_index(&mut self, info: &PakInfo) -> Result<(), PakReaderError> {
+        self.read_pak_index(info, true).await.map(|_| ())
+    }
+
+    async fn read_pak_index(&mut self, info: &PakInfo, verify: bool) -> Result<Vec<u8>, PakReaderError> {
+        let read_size = if info.is_encrypted {
+            round_up_to_block(info.index_size as usize)
+        } else {
+            info.index_size as usize
+        };
+
+        let mut buffer = self.io.read_at(info.index_offset as u64, read_size).await?;
+
+        if info.is_encrypted {
+            let key = self.get_encryption_key(info)?;
+            decrypt_ecb_in_place(&key, &mut buffer);
+            buffer.truncate(info.index_size as usize);
+        }
+
+        if verify {
+            let actual = sha1(&buffer);
+            if actual != info.index_hash {
+                return Err(PakReaderError::HashMismatch { expected: info.index_hash.clone(), actual });
+            }
+        }
+
         Ok(buffer)
     }
 
     async fn read_pak_info(&mut self, version_size: PakVersionSizes) -> Result<PakInfo, PakReaderError> {
         // start reading from the end
         let version_size = version_size as usize;
-        self.reader.seek(SeekFrom::End(-(version_size as i64)))?;
+        let total_len = self.io.len().await?;
+
+        if (version_size as u64) > total_len {
+            return Err(PakReaderError::ReadHeaderError);
+        }
 
         // initialize buffer, and reader
-        let mut header = self.reader.read_buffer(version_size).await?;
+        let header = self.io.read_at(total_len - version_size as u64, version_size).await?;
         let mut reader = &mut Cursor::new(header);
 
-        // reset the main cursor back to the start
-        self.reader.seek(SeekFrom::Start(0));
-
         // read the encryption guid
         let mut encryption_index_guid =  reader.read_buffer(16).await?;
 
@@ -270,10 +376,6 @@ impl PakReader {
             }
         }
 
-        if is_encrypted {
-            return Err(PakReaderError::EncryptionNotSupported)
-        }
-
         Ok(PakInfo {
             encryption_index_guid,
             is_encrypted,
@@ -293,4 +395,289 @@ impl PakCompressionBlock {
     pub fn get_size(&self) -> i64 {
         self.compression_end - self.compression_start
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_info(version: i32) -> PakInfo {
+        PakInfo {
+            encryption_index_guid: vec![],
+            is_encrypted: false,
+            magic: PAK_MAGIC,
+            version,
+            sub_version: 0,
+            index_offset: 0,
+            index_size: 0,
+            index_hash: vec![],
+            index_frozen: 0,
+            compression_methods: vec!["LZ4".into()],
+        }
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_rebases_relative_chunk_offsets_by_entry_offset() {
+        let plaintext = b"abcdefg";
+        let compressed_blocks: Vec<Vec<u8>> = vec![
+            lz4_flex::compress(&plaintext[0..4]),
+            lz4_flex::compress(&plaintext[4..7]),
+        ];
+
+        // Offsets are relative to entry.offset, not to the entry's payload
+        // (i.e. not entry.offset + entry.header_size)
+        let entry_offset = 100u64;
+        let mut compression_blocks = Vec::new();
+        let mut running = 20i64;
+        for block in &compressed_blocks {
+            let start = running;
+            running += block.len() as i64;
+            compression_blocks.push(PakCompressionBlock { compression_start: start, compression_end: running });
+        }
+
+        let total_len = entry_offset as usize + running as usize;
+        let mut buffer = vec![0u8; total_len];
+        for (block, pak_block) in compressed_blocks.iter().zip(&compression_blocks) {
+            let start = (entry_offset as i64 + pak_block.compression_start) as usize;
+            buffer[start..start + block.len()].copy_from_slice(block);
+        }
+
+        let entry = PakEntry {
+            start: 0,
+            offset: entry_offset,
+            size: compressed_blocks.iter().map(|b| b.len() as u64).sum(),
+            flags: 0,
+            timestamp: 0,
+            hash: vec![],
+            uncompressed_size: plaintext.len() as u64,
+            compression_index: 1,
+            compression_block_size: 4,
+            compression_blocks,
+            header_size: 12,
+        };
+
+        let info = test_info(PakVersions::RelativeChunkOffsets as i32);
+        let mut reader = PakReader::new(buffer);
+
+        let data = reader.get_pak_entry_data(&entry, &info, false).await.unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_treats_pre_v5_offsets_as_absolute() {
+        // Before PakVersions::RelativeChunkOffsets, compression_start/end are
+        // already absolute file offsets and must not be rebased by entry.offset
+        let plaintext = b"abcdefg";
+        let compressed = lz4_flex::compress(plaintext.as_slice());
+
+        let absolute_start = 50i64;
+        let mut buffer = vec![0u8; absolute_start as usize + compressed.len()];
+        buffer[absolute_start as usize..].copy_from_slice(&compressed);
+
+        let entry = PakEntry {
+            start: 0,
+            offset: 9999,
+            size: compressed.len() as u64,
+            flags: 0,
+            timestamp: 0,
+            hash: vec![],
+            uncompressed_size: plaintext.len() as u64,
+            compression_index: 1,
+            compression_block_size: plaintext.len() as u32,
+            compression_blocks: vec![PakCompressionBlock {
+                compression_start: absolute_start,
+                compression_end: absolute_start + compressed.len() as i64,
+            }],
+            header_size: 12,
+        };
+
+        let info = test_info(PakVersions::CompressionEncryption as i32);
+        let mut reader = PakReader::new(buffer);
+
+        let data = reader.get_pak_entry_data(&entry, &info, false).await.unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    fn test_entry(offset: u64, size: u64, hash: Vec<u8>) -> PakEntry {
+        PakEntry {
+            start: 0,
+            offset,
+            size,
+            flags: 0,
+            timestamp: 0,
+            hash,
+            uncompressed_size: size,
+            compression_index: 0,
+            compression_block_size: 0,
+            compression_blocks: vec![],
+            header_size: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_succeeds_when_hash_matches() {
+        let plaintext = b"abcdefg".to_vec();
+        let entry = test_entry(0, plaintext.len() as u64, sha1(&plaintext));
+        let info = test_info(PakVersions::Initial as i32);
+        let mut reader = PakReader::new(plaintext.clone());
+
+        let data = reader.get_pak_entry_data(&entry, &info, true).await.unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_fails_when_hash_mismatches() {
+        let plaintext = b"abcdefg".to_vec();
+        let entry = test_entry(0, plaintext.len() as u64, sha1(b"wrong bytes"));
+        let info = test_info(PakVersions::Initial as i32);
+        let mut reader = PakReader::new(plaintext);
+
+        let err = reader.get_pak_entry_data(&entry, &info, true).await.unwrap_err();
+        assert!(matches!(err, PakReaderError::HashMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn verify_index_succeeds_when_hash_matches() {
+        let index = b"mount point and entries go here".to_vec();
+        let mut info = test_info(PakVersions::Initial as i32);
+        info.index_offset = 0;
+        info.index_size = index.len() as i64;
+        info.index_hash = sha1(&index);
+
+        let mut reader = PakReader::new(index);
+        reader.verify_index(&info).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_index_fails_when_hash_mismatches() {
+        let index = b"mount point and entries go here".to_vec();
+        let mut info = test_info(PakVersions::Initial as i32);
+        info.index_offset = 0;
+        info.index_size = index.len() as i64;
+        info.index_hash = sha1(b"a different index");
+
+        let mut reader = PakReader::new(index);
+        let err = reader.verify_index(&info).await.unwrap_err();
+        assert!(matches!(err, PakReaderError::HashMismatch { .. }));
+    }
+
+    fn test_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        key
+    }
+
+    // Encrypts like a shipping pak would: AES-256 ECB over the data, padded
+    // with zeroes up to the next 16-byte block boundary
+    fn encrypt_ecb(key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+        use aes::cipher::{BlockEncrypt, KeyInit, generic_array::GenericArray};
+
+        let mut padded = data.to_vec();
+        padded.resize(round_up_to_block(data.len()), 0);
+
+        let cipher = aes::Aes256::new(GenericArray::from_slice(key));
+        for block in padded.chunks_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        }
+
+        padded
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_decrypts_uncompressed_entry() {
+        let key = test_key();
+        let guid: Guid = vec![1; 16];
+        let plaintext = b"hello encrypted world".to_vec();
+        let buffer = encrypt_ecb(&key, &plaintext);
+
+        let entry = PakEntry {
+            start: 0,
+            offset: 0,
+            size: plaintext.len() as u64,
+            flags: 0x01,
+            timestamp: 0,
+            hash: vec![],
+            uncompressed_size: plaintext.len() as u64,
+            compression_index: 0,
+            compression_block_size: 0,
+            compression_blocks: vec![],
+            header_size: 0,
+        };
+
+        let mut info = test_info(PakVersions::Initial as i32);
+        info.encryption_index_guid = guid.clone();
+
+        let mut keys = HashMap::new();
+        keys.insert(guid, key);
+        let mut reader = PakReader::with_keys(buffer, keys);
+
+        let data = reader.get_pak_entry_data(&entry, &info, false).await.unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[tokio::test]
+    async fn get_pak_entry_data_decrypts_compressed_entry() {
+        let key = test_key();
+        let guid: Guid = vec![2; 16];
+        let plaintext = b"abcdefg".to_vec();
+        let compressed = lz4_flex::compress(&plaintext);
+        let ciphertext = encrypt_ecb(&key, &compressed);
+
+        let entry_offset = 100u64;
+        let compression_start = 20i64;
+        let compression_end = compression_start + compressed.len() as i64;
+
+        let total_len = entry_offset as usize + compression_start as usize + ciphertext.len();
+        let mut buffer = vec![0u8; total_len];
+        let start = entry_offset as usize + compression_start as usize;
+        buffer[start..start + ciphertext.len()].copy_from_slice(&ciphertext);
+
+        let entry = PakEntry {
+            start: 0,
+            offset: entry_offset,
+            size: compressed.len() as u64,
+            flags: 0x01,
+            timestamp: 0,
+            hash: vec![],
+            uncompressed_size: plaintext.len() as u64,
+            compression_index: 1,
+            compression_block_size: plaintext.len() as u32,
+            compression_blocks: vec![PakCompressionBlock { compression_start, compression_end }],
+            header_size: 12,
+        };
+
+        let mut info = test_info(PakVersions::RelativeChunkOffsets as i32);
+        info.encryption_index_guid = guid.clone();
+
+        let mut keys = HashMap::new();
+        keys.insert(guid, key);
+        let mut reader = PakReader::with_keys(buffer, keys);
+
+        let data = reader.get_pak_entry_data(&entry, &info, false).await.unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[tokio::test]
+    async fn read_pak_index_decrypts_index() {
+        let key = test_key();
+        let guid: Guid = vec![3; 16];
+        let index = b"mount point and entries go here".to_vec();
+        let buffer = encrypt_ecb(&key, &index);
+
+        let mut info = test_info(PakVersions::Initial as i32);
+        info.is_encrypted = true;
+        info.encryption_index_guid = guid.clone();
+        info.index_offset = 0;
+        info.index_size = index.len() as i64;
+        info.index_hash = sha1(&index);
+
+        let mut keys = HashMap::new();
+        keys.insert(guid, key);
+        let mut reader = PakReader::with_keys(buffer, keys);
+
+        let decrypted = reader.read_pak_index(&info, true).await.unwrap();
+        assert_eq!(decrypted, index);
+    }
 }
\ No newline at end of file